@@ -0,0 +1,210 @@
+//! Acting on groups of copies: deleting, hardlinking, or symlinking the
+//! duplicates within each group down to a single "keeper".
+
+use core::fmt;
+use std::{
+    collections::HashSet,
+    fs,
+    os::unix,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::copies::PathIoError;
+
+/// What to do with the non-keeper files in each group of copies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Only report groups; don't touch the filesystem. The default.
+    Report,
+    /// Delete every duplicate, keeping only the chosen keeper.
+    Delete,
+    /// Replace every duplicate with a hard link to the keeper.
+    Hardlink,
+    /// Replace every duplicate with a symlink to the keeper.
+    Symlink,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Report => "report",
+            Action::Delete => "delete",
+            Action::Hardlink => "hardlink",
+            Action::Symlink => "symlink",
+        })
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report" => Ok(Action::Report),
+            "delete" => Ok(Action::Delete),
+            "hardlink" => Ok(Action::Hardlink),
+            "symlink" => Ok(Action::Symlink),
+            other => Err(format!("unrecognized action: {other}")),
+        }
+    }
+}
+
+/// Which file in a group of copies to leave untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// Keep the file with the shortest path.
+    ShortestPath,
+    /// Keep the file with the oldest modification time.
+    OldestModified,
+    /// Keep the first path, lexicographically.
+    FirstLexicographic,
+}
+
+impl KeepPolicy {
+    /// Returns the index within `group` of the file this policy would keep.
+    pub fn keeper_index(&self, group: &[PathBuf]) -> usize {
+        let index = match self {
+            KeepPolicy::ShortestPath => group
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, path)| path.as_os_str().len()),
+            // Only consider files whose mtime was actually readable: a file
+            // that fails to stat is not "older than everything" just
+            // because we have no timestamp for it.
+            KeepPolicy::OldestModified => group
+                .iter()
+                .enumerate()
+                .filter_map(|(i, path)| {
+                    let modified = fs::metadata(path).ok()?.modified().ok()?;
+                    Some((i, path, modified))
+                })
+                .min_by_key(|(_, _, modified)| *modified)
+                .map(|(i, path, _)| (i, path)),
+            KeepPolicy::FirstLexicographic => group.iter().enumerate().min_by(|(_, a), (_, b)| a.cmp(b)),
+        };
+        index.map_or(0, |(i, _)| i)
+    }
+}
+
+impl fmt::Display for KeepPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeepPolicy::ShortestPath => "shortest-path",
+            KeepPolicy::OldestModified => "oldest-mtime",
+            KeepPolicy::FirstLexicographic => "first-lexicographic",
+        })
+    }
+}
+
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "shortest-path" => Ok(KeepPolicy::ShortestPath),
+            "oldest-mtime" => Ok(KeepPolicy::OldestModified),
+            "first-lexicographic" => Ok(KeepPolicy::FirstLexicographic),
+            other => Err(format!("unrecognized keep policy: {other}")),
+        }
+    }
+}
+
+/// Applies `action` to every group with at least two copies, keeping one
+/// file per group as chosen by `keep` and replacing or removing the rest.
+///
+/// Each file is handled independently: a failure on one duplicate is
+/// surfaced as a [`PathIoError`] rather than aborting the run, so the
+/// remaining files in the group (and in other groups) are still processed.
+/// Replacing a file with a hardlink or symlink is transactional — the link
+/// is created at a temporary sibling path and only swapped into place once
+/// it has been created successfully, so an interrupted run never deletes a
+/// duplicate without its replacement ready.
+pub fn apply_action(groups: &[Vec<PathBuf>], action: Action, keep: KeepPolicy) -> (Vec<PathBuf>, Vec<PathIoError>) {
+    let mut affected = Vec::new();
+    let mut errors = Vec::new();
+
+    if action == Action::Report {
+        return (affected, errors);
+    }
+
+    for group in groups {
+        // Overlapping input paths (e.g. nested base paths on the CLI) can
+        // cause the same on-disk file to appear twice in a group. Treating
+        // two occurrences of one path as separate copies would pick one as
+        // the "duplicate" of itself and delete/relink the file out from
+        // under its own keeper, so only act on distinct paths.
+        let mut seen = HashSet::new();
+        let group: Vec<PathBuf> = group.iter().filter(|path| seen.insert(path.as_path())).cloned().collect();
+
+        if group.len() < 2 {
+            continue;
+        }
+
+        let keeper_index = keep.keeper_index(&group);
+        let keeper = &group[keeper_index];
+
+        for (i, duplicate) in group.iter().enumerate() {
+            if i == keeper_index {
+                continue;
+            }
+
+            let result = match action {
+                Action::Report => Ok(()),
+                Action::Delete => fs::remove_file(duplicate),
+                Action::Hardlink => replace_with_link(duplicate, |tmp_path| fs::hard_link(keeper, tmp_path)),
+                // A symlink's relative target is resolved against the
+                // link's own parent directory, not the keeper's, so a
+                // target collected relative to some other base path (the
+                // common case here, since the keeper and duplicate usually
+                // live in different directories) would silently point
+                // nowhere. Canonicalizing first keeps the link valid
+                // regardless of where `duplicate` and `keeper` sit relative
+                // to each other.
+                Action::Symlink => fs::canonicalize(keeper)
+                    .and_then(|target| replace_with_link(duplicate, |tmp_path| unix::fs::symlink(target, tmp_path))),
+            };
+
+            match result {
+                Ok(()) => affected.push(duplicate.clone()),
+                Err(error) => errors.push(PathIoError::new(duplicate.clone(), error)),
+            }
+        }
+    }
+
+    (affected, errors)
+}
+
+/// Replaces `path` with a link created by `create_link`, without ever
+/// leaving `path` missing or losing its contents if the process is
+/// interrupted or the link is malformed: the link is built at a temporary
+/// sibling path first, its target is read back and compared against
+/// `path`'s current contents, and `path` is only replaced (by renaming the
+/// temporary link over it) once that link has been confirmed to resolve to
+/// the same data.
+fn replace_with_link(path: &Path, create_link: impl FnOnce(&Path) -> std::io::Result<()>) -> std::io::Result<()> {
+    let tmp_path = tmp_sibling(path);
+    create_link(&tmp_path)?;
+
+    let verified = matches!((fs::read(&tmp_path), fs::read(path)), (Ok(a), Ok(b)) if a == b);
+    if !verified {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(std::io::Error::other(format!(
+            "link at {} does not resolve to the same contents as {}",
+            tmp_path.display(),
+            path.display()
+        )));
+    }
+
+    // On Unix, rename atomically replaces an existing file, so `path` is
+    // never absent in between.
+    fs::rename(&tmp_path, path)
+}
+
+/// Returns a sibling path of `path` suitable for staging a replacement
+/// before it is renamed into place.
+fn tmp_sibling(path: &Path) -> PathBuf {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    path.with_file_name(tmp_name)
+}
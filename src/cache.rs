@@ -0,0 +1,90 @@
+//! A persistent on-disk cache of file content hashes.
+//!
+//! Re-scanning the same trees repeatedly re-hashes files whose contents
+//! haven't changed. [`HashCache`] remembers the digest computed for each
+//! path the last time it was scanned, keyed by that file's size and
+//! modification time, so unchanged files are not read in full again.
+//! [`crate::copies::get_copies_hashed`] still reads the first block of every
+//! candidate file, cached or not, for its partial-hash stage.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::HashType;
+
+/// The hash type, size, and modification time a cached digest was computed
+/// against.
+///
+/// A cached digest is only reused while all three still match the file's
+/// current [`fs::metadata`] and the current run's `--hash`; a digest
+/// computed under a different [`HashType`] is a different value entirely and
+/// must never be confused for one computed under the type in use now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheKey {
+    hash_type: HashType,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// A persistent cache mapping a file's path to the digest computed for it,
+/// validated against the file's size and modification time.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HashCache {
+    entries: HashMap<PathBuf, (CacheKey, Vec<u8>)>,
+}
+
+impl HashCache {
+    /// Loads a cache from `path`, returning an empty cache if the file
+    /// doesn't exist or can't be parsed.
+    pub fn load(path: &Path) -> HashCache {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the cached digest for `path`, if its current size and
+    /// modification time still match the cached entry and that entry was
+    /// computed under `hash_type`.
+    pub fn get(&self, path: &Path, hash_type: HashType) -> Option<&Vec<u8>> {
+        let metadata = fs::metadata(path).ok()?;
+        let key = CacheKey {
+            hash_type,
+            size: metadata.len(),
+            modified: metadata.modified().ok()?,
+        };
+        let (cached_key, digest) = self.entries.get(path)?;
+        (*cached_key == key).then_some(digest)
+    }
+
+    /// Records the digest computed for `path` under its current size and
+    /// modification time, and the `hash_type` it was computed with.
+    pub fn insert(&mut self, path: PathBuf, hash_type: HashType, digest: Vec<u8>) {
+        let Ok(metadata) = fs::metadata(&path) else {
+            return;
+        };
+        let Ok(modified) = metadata.modified() else {
+            return;
+        };
+        let key = CacheKey {
+            hash_type,
+            size: metadata.len(),
+            modified,
+        };
+        self.entries.insert(path, (key, digest));
+    }
+
+    /// Prunes entries whose paths no longer exist, then writes the cache to
+    /// `path` as JSON.
+    pub fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        self.entries.retain(|cached_path, _| cached_path.exists());
+        let bytes = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        fs::write(path, bytes)
+    }
+}
@@ -4,16 +4,41 @@ use core::fmt;
 use std::{
     collections::HashMap,
     fs::{self, File},
-    io::{Read, Seek},
+    hash::Hash,
+    io::Read,
     path::{Path, PathBuf},
 };
 
+use rayon::prelude::*;
+
+use crate::{cache::HashCache, hash::HashType};
+
+/// Number of leading bytes read from a file for the partial-hash stage of
+/// [`get_copies_hashed`].
+const PARTIAL_HASH_BYTES: usize = 4096;
+
 /// Struct which wraps a std::io::Error to include the path for which the error occurred.
 pub struct PathIoError {
     error: std::io::Error,
     path: PathBuf,
 }
 
+impl PathIoError {
+    pub(crate) fn new(path: PathBuf, error: std::io::Error) -> PathIoError {
+        PathIoError { error, path }
+    }
+
+    /// The path for which the error occurred.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The underlying I/O error.
+    pub fn error(&self) -> &std::io::Error {
+        &self.error
+    }
+}
+
 impl fmt::Display for PathIoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -110,80 +135,43 @@ pub fn get_copies(paths: &[PathBuf]) -> (Vec<Vec<PathBuf>>, Vec<PathIoError>) {
         };
     }
 
-    let mut copies = HashMap::new();
-    let mut errors = Vec::new();
-    for path in paths {
-        match fs::read(&path) {
-            Ok(contents) => {
-                copies.entry(contents).or_insert(vec![]).push(path.clone());
-            },
-            Err(err) => {
-                errors.push(PathIoError { error: err, path: path.to_path_buf()})
-            },
-        }
-    }
-    (copies.values().cloned().collect(), errors)
-}
-
-/// Returns a "fingerprint" for a file which should uniquely identify its contents with high probability.
-///
-/// This runs in constant time; for small files (under one page in length),
-/// the entire file is read, and we concatenate first byte of each eighth of
-/// the file to build the fingerprint. For larger files, we seek each eighth
-/// of the file instead, again taking the first byte of each. Additionally,
-/// we include the length of the file in the identifier.
-/// 
-/// # Errors
-///
-/// This function will return an error if the file cannot be opened or read,
-/// or if a seek fails while iterating over the file contents.
-pub fn get_fingerprint(path: &Path) -> Result<(usize, usize), std::io::Error> {
-    let mut file = File::open(&path)?;
-    let len = file.metadata().map_or(0, |md| md.len()) as usize;
-
-    if len == 0 {
-        return Ok((0, 0));
-    }
-
-    let mut contents: Vec<u8> = Vec::with_capacity(len.min(64));
-    if len <= 4096 {
-        let bytes = fs::read(&path)?;
-        for i in (0..len).step_by((len / 8).max(1)) {
-            contents.push(bytes[i]);
-        }
-    } else {
-        let steps = 4;
-        let read_size = 8 / steps;
-        let step = (len / steps) as i64;
-        let mut buf: Vec<u8> = (0..read_size).map(|_| 0).collect();
-        for _ in 0..steps {
-            file.read_exact(&mut buf)?;
-            for &b in buf.iter() {
-                contents.push(b);
-            }
-            file.seek(std::io::SeekFrom::Current(step))?;
-        }
-    }
-
-    let mut key = 0;
-    for (i, &c) in contents.iter().enumerate() {
-        key |= (c as usize) << (i << 3);
-    }
-
-    Ok((key, len))
+    let (copies, errors) = partition_by(paths, |path| fs::read(path));
+    (copies.into_values().collect(), errors)
 }
 
 /// Returns a list of all sets of copies of files in a set of paths in a memory efficient manner.
-/// 
+///
 /// Each entry in the output list will be a list of files from the input paths
 /// which have the same contents. The order of the output is not deterministic.
 ///
+/// This runs as a three-stage pipeline so that files are only read as far as
+/// necessary to exclude them:
+/// 1. Paths are grouped by [`fs::metadata`] length; a size shared by only one
+///    file can't have a copy, so it is reported immediately without reading
+///    the file at all.
+/// 2. Each remaining size group is re-partitioned by a digest (using
+///    `hash_type`) of only the first [`PARTIAL_HASH_BYTES`] bytes of each
+///    file.
+/// 3. Each remaining (size, partial hash) group is re-partitioned by a
+///    digest of the full file contents. If `hash_type` is
+///    collision-resistant (see [`HashType::is_collision_resistant`]),
+///    matching digests are trusted directly; otherwise the group is
+///    confirmed with a byte-for-byte comparison via [`get_copies`].
+///
 /// Runs in O(NF) time and O(n) memory in expectation, where N is the total
 /// number of files, n is the number of unique files, and F is the average
-/// file size.
-/// 
+/// file size, but in practice reads far fewer bytes than that bound since
+/// most files are excluded by size or by their first block. Each stage
+/// hashes/reads its candidate paths in parallel via rayon.
+///
+/// If `cache` is provided, the full-content digest for a path is reused from
+/// the cache instead of re-reading the whole file, as long as the file's size
+/// and modification time still match the cached entry; freshly computed
+/// digests are written back into the cache for the caller to persist. The
+/// cache is not consulted in stage 2, so the first block is still read.
+///
 /// # Errors
-/// 
+///
 /// Any errors reading files will be included in the second index of the
 /// return value.
 ///
@@ -198,7 +186,7 @@ pub fn get_fingerprint(path: &Path) -> Result<(usize, usize), std::io::Error> {
 ///     Path::from("files/more_files/even_more_files/e.txt"),
 ///     Path::from("files/more_files/even_more_files/f.txt")
 /// ];
-/// let (copies, errors) = get_copies(&paths);
+/// let (copies, errors) = get_copies_hashed(&paths, HashType::Blake3, None);
 /// let expected = vec![
 ///     vec!["files/a.txt", "files/more_files/even_more_files/e.txt"],
 ///     vec!["files/b.txt", "files/more_files/c.txt", "files/more_files.d.txt"]
@@ -206,31 +194,144 @@ pub fn get_fingerprint(path: &Path) -> Result<(usize, usize), std::io::Error> {
 /// assert!(copies == expected);
 /// assert!(errors.len() == 0);
 /// ```
-pub fn get_copies_hashed(paths: &[PathBuf]) -> (Vec<Vec<PathBuf>>, Vec<PathIoError>) {
-    let mut candidate_copies = HashMap::new();
-    let mut errors: Vec<PathIoError> = Vec::new();
-
-    for path in paths {
-        match get_fingerprint(path) {
-            Ok(fingerprint) => {
-                candidate_copies
-                    .entry(fingerprint)
-                    .or_insert(vec![])
-                    .push(path.clone());
+pub fn get_copies_hashed(
+    paths: &[PathBuf],
+    hash_type: HashType,
+    mut cache: Option<&mut HashCache>,
+) -> (Vec<Vec<PathBuf>>, Vec<PathIoError>) {
+    let mut copies: Vec<Vec<PathBuf>> = Vec::new();
+
+    // Stage 1: group by size. Sizes with a single file can't have a copy.
+    let (by_size, mut errors) = partition_by(paths, |path| fs::metadata(path).map(|md| md.len()));
+
+    for size_group in by_size.values() {
+        if size_group.len() < 2 {
+            copies.push(size_group.clone());
+            continue;
+        }
+
+        // Stage 2: re-partition by a hash of only the first block of each file.
+        let (by_partial_hash, partial_errors) = partition_by(size_group, |path| {
+            read_partial(path, PARTIAL_HASH_BYTES).map(|partial| hash_type.hash_bytes(&partial))
+        });
+        errors.extend(partial_errors);
+
+        for partial_group in by_partial_hash.values() {
+            if partial_group.len() < 2 {
+                copies.push(partial_group.clone());
+                continue;
+            }
+
+            // Stage 3: re-partition by a hash of the full file contents,
+            // reusing the cache where possible.
+            let full_hash = hash_full_contents(partial_group, hash_type, cache.as_deref());
+            errors.extend(full_hash.errors);
+            if let Some(cache) = cache.as_mut() {
+                for (path, digest) in full_hash.computed {
+                    cache.insert(path, hash_type, digest);
+                }
+            }
+
+            for full_group in full_hash.groups.values() {
+                if hash_type.is_collision_resistant() || full_group.len() < 2 {
+                    copies.push(full_group.clone());
+                } else {
+                    let (group_copies, group_errors) = get_copies(full_group);
+                    copies.extend(group_copies);
+                    errors.extend(group_errors);
+                }
+            }
+        }
+    }
+
+    (copies, errors)
+}
+
+/// The outcome of digesting a group of paths in [`hash_full_contents`].
+struct FullHashResult {
+    /// The paths, grouped by full-content digest.
+    groups: HashMap<Vec<u8>, Vec<PathBuf>>,
+    /// Any I/O errors hit while reading a path.
+    errors: Vec<PathIoError>,
+    /// Every `(path, digest)` pair that was computed or reused, for the
+    /// caller to merge back into its cache.
+    computed: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Digests the full contents of each path in parallel, consulting `cache`
+/// for a hit before reading the file.
+fn hash_full_contents(paths: &[PathBuf], hash_type: HashType, cache: Option<&HashCache>) -> FullHashResult {
+    let results: Vec<Result<(PathBuf, Vec<u8>), PathIoError>> = paths
+        .par_iter()
+        .map(|path| {
+            if let Some(digest) = cache.and_then(|cache| cache.get(path, hash_type)) {
+                return Ok((path.clone(), digest.clone()));
+            }
+            match fs::read(path) {
+                Ok(contents) => Ok((path.clone(), hash_type.hash_bytes(&contents))),
+                Err(error) => Err(PathIoError {
+                    error,
+                    path: path.clone(),
+                }),
             }
-            Err(err) => errors.push(PathIoError {
-                error: err,
-                path: path.to_path_buf(),
+        })
+        .collect();
+
+    let mut groups: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+    let mut errors = Vec::new();
+    let mut computed = Vec::new();
+    for result in results {
+        match result {
+            Ok((path, digest)) => {
+                groups.entry(digest.clone()).or_insert(vec![]).push(path.clone());
+                computed.push((path, digest));
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    FullHashResult { groups, errors, computed }
+}
+
+/// Computes `f` for every path in parallel and groups the paths by the
+/// resulting key, collecting any I/O errors along the way.
+fn partition_by<K, F>(paths: &[PathBuf], f: F) -> (HashMap<K, Vec<PathBuf>>, Vec<PathIoError>)
+where
+    K: Eq + Hash + Send,
+    F: Fn(&Path) -> Result<K, std::io::Error> + Sync,
+{
+    let results: Vec<Result<(K, PathBuf), PathIoError>> = paths
+        .par_iter()
+        .map(|path| match f(path) {
+            Ok(key) => Ok((key, path.clone())),
+            Err(error) => Err(PathIoError {
+                error,
+                path: path.clone(),
             }),
+        })
+        .collect();
+
+    let mut groups: HashMap<K, Vec<PathBuf>> = HashMap::new();
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok((key, path)) => groups.entry(key).or_insert(vec![]).push(path),
+            Err(err) => errors.push(err),
         }
     }
+    (groups, errors)
+}
 
-    let mut copies: Vec<Vec<PathBuf>> = Vec::new();
-    for group in candidate_copies.values() {
-        let (group_copies, group_errors) = get_copies(group);
-        copies.extend(group_copies);
-        errors.extend(group_errors);
+/// Reads up to `max_bytes` bytes from the start of the file at `path`.
+fn read_partial(path: &Path, max_bytes: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; max_bytes];
+    let mut total_read = 0;
+    loop {
+        match file.read(&mut buf[total_read..])? {
+            0 => break,
+            n => total_read += n,
+        }
     }
-    
-    (copies, errors)
+    buf.truncate(total_read);
+    Ok(buf)
 }
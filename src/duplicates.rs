@@ -1,11 +1,10 @@
 //! Functions for detecting duplicates in a set of paths.
 
-use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    fs,
-    hash::{Hash, Hasher},
-    path::PathBuf,
-};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use rayon::prelude::*;
+
+use crate::hash::HashType;
 
 
 /// Returns a list of all sets of duplicate files in a set of paths.
@@ -59,49 +58,59 @@ pub fn get_duplicates(paths: &[PathBuf]) -> Vec<Vec<PathBuf>> {
 }
 
 /// Returns a list of all sets of duplicate files in a set of paths in a memory efficient manner.
-/// 
-/// Each entry in the output list will be a list of files from the input paths 
+///
+/// Each entry in the output list will be a list of files from the input paths
 /// which have the same contents. The order of the output is nondeterministic.
-/// 
-/// Runs in O(NF) time and O(n) memory in expectation, where N is the total 
-/// number of files, n is the number of unique files, and F is the average 
-/// file size.
-/// 
+///
+/// Files are grouped by digesting their full contents with `hash_type`. If
+/// `hash_type` is collision-resistant (see
+/// [`HashType::is_collision_resistant`]), candidate groups are trusted
+/// without re-reading their contents; otherwise each group is confirmed
+/// with a byte-for-byte comparison via [`get_duplicates`].
+///
+/// Runs in O(NF) time and O(n) memory in expectation, where N is the total
+/// number of files, n is the number of unique files, and F is the average
+/// file size. Files are hashed in parallel via rayon.
+///
 /// ## Example
 /// ```no_run
-/// // assume that a.txt and e.txt have the same contents, and b.txt, c.txt and d.txt have the same contents 
+/// // assume that a.txt and e.txt have the same contents, and b.txt, c.txt and d.txt have the same contents
 /// let paths = vec![
-///     Path::from("files/a.txt"), 
-///     Path::from("files/b.txt"), 
+///     Path::from("files/a.txt"),
+///     Path::from("files/b.txt"),
 ///     Path::from("files/more_files/c.txt"),
 ///     Path::from("files/more_files/d.txt"),
 ///     Path::from("files/more_files/even_more_files/e.txt"),
 ///     Path::from("files/more_files/even_more_files/f.txt")
 /// ];
-/// let duplicates = get_duplicates(&paths);
+/// let duplicates = get_duplicates_hashed(&paths, HashType::Blake3);
 /// let expected = vec![
 ///     vec!["files/a.txt", "files/more_files/even_more_files/e.txt"],
 ///     vec!["files/b.txt", "files/more_files/c.txt", "files/more_files.d.txt"]
 /// ];
 /// assert!(duplicates == expected);
 /// ```
-pub fn get_duplicates_hashed(paths: &[PathBuf]) -> Vec<Vec<PathBuf>> {
-    let mut candidate_duplicates: HashMap<(u64, usize), Vec<PathBuf>> = HashMap::new();
-    for path in paths.iter().cloned() {
-        let contents = fs::read(&path);
-        if let Ok(contents) = contents {
-            let mut hasher = DefaultHasher::new();
-            contents.hash(&mut hasher);
-            let contents_hash = hasher.finish();
-            let key = (contents_hash, contents.len());
-            candidate_duplicates.entry(key).or_insert(vec![]).push(path);
-        }
+pub fn get_duplicates_hashed(paths: &[PathBuf], hash_type: HashType) -> Vec<Vec<PathBuf>> {
+    let digests: Vec<(Vec<u8>, PathBuf)> = paths
+        .par_iter()
+        .filter_map(|path| fs::read(path).ok().map(|contents| (hash_type.hash_bytes(&contents), path.clone())))
+        .collect();
+
+    let mut candidate_duplicates: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+    for (digest, path) in digests {
+        candidate_duplicates.entry(digest).or_insert(vec![]).push(path);
     }
 
     candidate_duplicates
         .values()
         .filter(|candidates| candidates.len() > 1)
-        .map(|candidates| get_duplicates(candidates))
+        .map(|candidates| {
+            if hash_type.is_collision_resistant() {
+                vec![candidates.clone()]
+            } else {
+                get_duplicates(candidates)
+            }
+        })
         .flatten()
         .collect()
 }
@@ -0,0 +1,55 @@
+//! Filters applied while traversing a directory tree, so that irrelevant
+//! files and directories are excluded before the expensive copy-detection
+//! stages ever see them.
+
+use std::path::Path;
+
+use glob::Pattern;
+
+/// Bounds and exclusions applied by [`crate::paths::get_descendants`] during
+/// traversal.
+///
+/// Directories matching any pattern in `exclude_glob` are not descended
+/// into at all. Files are dropped if their extension isn't in
+/// `include_ext` (when set), is in `exclude_ext`, or their size falls
+/// outside `[min_size, max_size]`.
+#[derive(Clone, Debug, Default)]
+pub struct TraversalFilter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub include_ext: Option<Vec<String>>,
+    pub exclude_ext: Vec<String>,
+    pub exclude_glob: Vec<Pattern>,
+}
+
+impl TraversalFilter {
+    /// Returns whether `path` (a directory) should not be descended into.
+    pub fn excludes_dir(&self, path: &Path) -> bool {
+        self.exclude_glob.iter().any(|pattern| pattern.matches_path(path))
+    }
+
+    /// Returns whether the file at `path`, with the given length, passes
+    /// the size bounds and extension allow/deny lists.
+    pub fn allows_file(&self, path: &Path, len: u64) -> bool {
+        if self.min_size.is_some_and(|min_size| len < min_size) {
+            return false;
+        }
+        if self.max_size.is_some_and(|max_size| len > max_size) {
+            return false;
+        }
+
+        let ext = path.extension().and_then(|ext| ext.to_str());
+
+        if let Some(include_ext) = &self.include_ext {
+            if !ext.is_some_and(|ext| include_ext.iter().any(|allowed| allowed.eq_ignore_ascii_case(ext))) {
+                return false;
+            }
+        }
+
+        if ext.is_some_and(|ext| self.exclude_ext.iter().any(|denied| denied.eq_ignore_ascii_case(ext))) {
+            return false;
+        }
+
+        true
+    }
+}
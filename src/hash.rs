@@ -0,0 +1,109 @@
+//! Pluggable hash backends used to fingerprint file contents.
+//!
+//! [`HashType`] selects the algorithm used to digest a file's contents.
+//! `Blake3` is collision-resistant enough that two files with matching
+//! digests can be assumed identical without re-reading them; `Xxh3` and
+//! `Crc32` are faster but carry a (very small) risk of a false positive,
+//! so callers that need certainty should prefer `Blake3`.
+
+use core::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// The hash algorithm used to digest file contents when looking for copies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashType {
+    /// Blake3, a cryptographic hash. Collisions are not a practical concern,
+    /// so matching digests can be trusted without re-reading the files.
+    Blake3,
+    /// xxh3, a fast non-cryptographic hash, using its 128-bit digest.
+    Xxh3,
+    /// CRC32, a fast checksum with a comparatively high collision rate.
+    Crc32,
+}
+
+impl HashType {
+    /// Returns whether a matching digest from this hash type can be trusted
+    /// without reading the files again to confirm the match.
+    pub fn is_collision_resistant(&self) -> bool {
+        matches!(self, HashType::Blake3)
+    }
+
+    /// Hashes `bytes` and returns the resulting digest.
+    pub fn hash_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut hasher = self.new_hasher();
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    fn new_hasher(&self) -> Box<dyn DigestHasher> {
+        match self {
+            HashType::Blake3 => Box::new(blake3::Hasher::new()),
+            HashType::Xxh3 => Box::new(xxhash_rust::xxh3::Xxh3::new()),
+            HashType::Crc32 => Box::new(crc32fast::Hasher::new()),
+        }
+    }
+}
+
+impl fmt::Display for HashType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashType::Blake3 => "blake3",
+            HashType::Xxh3 => "xxh3",
+            HashType::Crc32 => "crc32",
+        })
+    }
+}
+
+impl FromStr for HashType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "blake3" => Ok(HashType::Blake3),
+            "xxh3" => Ok(HashType::Xxh3),
+            "crc32" => Ok(HashType::Crc32),
+            other => Err(format!("unrecognized hash type: {other}")),
+        }
+    }
+}
+
+/// A hasher which can be fed bytes incrementally and finalized into a digest.
+///
+/// This exists so that [`HashType`] can box whichever concrete hasher
+/// implementation it needs behind a single interface.
+trait DigestHasher {
+    fn update(&mut self, bytes: &[u8]);
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+impl DigestHasher for blake3::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        blake3::Hasher::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.finalize().as_bytes().to_vec()
+    }
+}
+
+impl DigestHasher for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, bytes: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.digest128().to_be_bytes().to_vec()
+    }
+}
+
+impl DigestHasher for crc32fast::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        crc32fast::Hasher::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.finalize().to_be_bytes().to_vec()
+    }
+}
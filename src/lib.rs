@@ -35,5 +35,10 @@
 //! assert!(duplicates == expected);
 //! ```
 
+pub mod actions;
+pub mod cache;
+pub mod copies;
 pub mod duplicates;
+pub mod filter;
+pub mod hash;
 pub mod paths;
\ No newline at end of file
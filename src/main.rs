@@ -1,8 +1,96 @@
-use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+use core::fmt;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+    str::FromStr,
+};
 
 use clap::Parser;
 use colored::{Color, Colorize};
-use duplicates::{copies::get_copies_hashed, paths::{get_common_prefix, get_descendants}};
+use duplicates::{
+    actions::{apply_action, Action, KeepPolicy},
+    cache::HashCache,
+    copies::{get_copies_hashed, PathIoError},
+    filter::TraversalFilter,
+    hash::HashType,
+    paths::{get_common_prefix, get_descendants},
+};
+use glob::Pattern;
+use serde::Serialize;
+
+/// Output format for the reported duplicate groups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Colored, separator-joined text meant for human reading.
+    Text,
+    /// Structured JSON meant for scripts and pipelines.
+    Json,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Text => "text",
+            Format::Json => "json",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unrecognized format: {other}")),
+        }
+    }
+}
+
+/// A single group of copies, shaped for JSON output.
+#[derive(Serialize)]
+struct DuplicateGroupJson {
+    count: usize,
+    reclaimable_bytes: u64,
+    common_prefix: String,
+    paths: Vec<String>,
+}
+
+/// Returns every path in `groups` that `keep` would act on (i.e. every
+/// non-keeper file in a group with at least two distinct paths), matching
+/// what [`apply_action`] would touch.
+fn planned_duplicates(groups: &[Vec<PathBuf>], keep: KeepPolicy) -> Vec<PathBuf> {
+    groups
+        .iter()
+        .flat_map(|group| {
+            let mut seen = HashSet::new();
+            let group: Vec<PathBuf> = group.iter().filter(|path| seen.insert(path.as_path())).cloned().collect();
+            if group.len() < 2 {
+                return Vec::new();
+            }
+            let keeper_index = keep.keeper_index(&group);
+            group.into_iter().enumerate().filter(|(i, _)| *i != keeper_index).map(|(_, path)| path).collect()
+        })
+        .collect()
+}
+
+/// A single I/O error, shaped for JSON output.
+#[derive(Serialize)]
+struct PathIoErrorJson {
+    path: String,
+    error: String,
+}
+
+impl From<&PathIoError> for PathIoErrorJson {
+    fn from(error: &PathIoError) -> PathIoErrorJson {
+        PathIoErrorJson {
+            path: error.path().display().to_string(),
+            error: error.error().to_string(),
+        }
+    }
+}
 
 /// Command line arguments used when running this crate as a script.
 #[derive(Parser)]
@@ -22,7 +110,55 @@ struct Cli {
     #[clap(short, long)]
     max_depth: Option<usize>,
     #[clap(short, long)]
-    no_color_suffixes: bool
+    no_color_suffixes: bool,
+    /// Hash algorithm used to digest file contents: blake3, xxh3, or crc32.
+    #[clap(long, default_value_t = HashType::Blake3)]
+    hash: HashType,
+    /// Number of threads to hash/traverse with. 0 uses all available cores.
+    #[clap(long, default_value_t = 0)]
+    threads: usize,
+    /// Path to a persistent cache of previously computed file hashes. If
+    /// given, unchanged files are not read and hashed in full again on
+    /// subsequent runs; their first block is still read.
+    #[clap(long)]
+    cache: Option<PathBuf>,
+    /// Exclude files smaller than this size, in bytes.
+    #[clap(long)]
+    min_size: Option<u64>,
+    /// Exclude files larger than this size, in bytes.
+    #[clap(long)]
+    max_size: Option<u64>,
+    /// Only include files with one of these extensions.
+    #[clap(long)]
+    include_ext: Vec<String>,
+    /// Exclude files with one of these extensions.
+    #[clap(long)]
+    exclude_ext: Vec<String>,
+    /// Exclude directories matching this glob pattern from traversal.
+    /// Patterns are matched against the full path, not just the directory
+    /// name, so excluding a directory by name anywhere in the tree requires
+    /// a leading `**/`, e.g. `--exclude-glob '**/target'` rather than
+    /// `--exclude-glob target`.
+    #[clap(long)]
+    exclude_glob: Vec<Pattern>,
+    /// What to do with the duplicates in each group: report, delete,
+    /// hardlink, or symlink. Defaults to only reporting.
+    #[clap(long, default_value_t = Action::Report)]
+    action: Action,
+    /// Which file in a group to keep untouched when `--action` removes or
+    /// replaces the rest: shortest-path, oldest-mtime, or first-lexicographic.
+    #[clap(long, default_value_t = KeepPolicy::ShortestPath)]
+    keep: KeepPolicy,
+    /// Print what `--action` would do without touching the filesystem.
+    #[clap(long)]
+    dry_run: bool,
+    /// Actually perform `--action` on the filesystem. Required (along with
+    /// `--action`) since `--dry-run` alone will not modify any files.
+    #[clap(long)]
+    confirm: bool,
+    /// Output format for the reported duplicate groups: text or json.
+    #[clap(long, default_value_t = Format::Text)]
+    format: Format,
 }
 
 /// Outputs all of the duplicate files from the descendants of a base_path.
@@ -30,50 +166,134 @@ struct Cli {
 /// See the (README.md) for usage details.
 fn main() {
     let args = Cli::parse();
+
+    if args.threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(args.threads)
+            .build_global()
+            .expect("failed to configure the rayon thread pool");
+    }
+
+    let filter = TraversalFilter {
+        min_size: args.min_size,
+        max_size: args.max_size,
+        include_ext: if args.include_ext.is_empty() { None } else { Some(args.include_ext.clone()) },
+        exclude_ext: args.exclude_ext.clone(),
+        exclude_glob: args.exclude_glob.clone(),
+    };
+
     let base_paths = args.paths.iter().map(|s| Path::new(s));
     let descendants: Vec<PathBuf> = base_paths
-        .map(|path| get_descendants(path, args.max_depth))
+        .map(|path| get_descendants(path, args.max_depth, &filter))
         .flatten()
         .collect();
-    let (copies, errors) = get_copies_hashed(&descendants);
-    for paths in copies {
-        let count = paths.len();
-        if count < args.min_count || args.max_count.is_some_and(|max_count| count > max_count) {
-            continue;
-        }
+    if args.action != Action::Report && args.dry_run == args.confirm {
+        eprintln!(
+            "{}",
+            "--action requires exactly one of --dry-run or --confirm".color(Color::BrightRed)
+        );
+        std::process::exit(1);
+    }
+
+    let mut hash_cache = args.cache.as_ref().map(|path| HashCache::load(path));
+    let (copies, errors) = get_copies_hashed(&descendants, args.hash, hash_cache.as_mut());
+    let reported: Vec<Vec<PathBuf>> = copies
+        .into_iter()
+        .filter(|paths| {
+            let count = paths.len();
+            count >= args.min_count && !args.max_count.is_some_and(|max_count| count > max_count)
+        })
+        .collect();
 
-        let path_strings: Vec<String> = if args.no_color_suffixes {
-            paths.iter().map(|path| path.display().to_string()).collect()
-        } else {
-            let common_prefix = get_common_prefix(&paths);
-            let common_prefix_string = common_prefix.display().to_string().color(Color::Cyan);
-            paths.iter().map(|path| {
-                let stripped = path.strip_prefix(&common_prefix);
-                if let Ok(stripped) = stripped {
-                    format!("{}{}{}", common_prefix_string, MAIN_SEPARATOR_STR, stripped.display())
+    match args.format {
+        Format::Text => {
+            for paths in &reported {
+                let count = paths.len();
+                let path_strings: Vec<String> = if args.no_color_suffixes {
+                    paths.iter().map(|path| path.display().to_string()).collect()
                 } else {
-                    path.display().to_string()
+                    let common_prefix = get_common_prefix(paths);
+                    let common_prefix_string = common_prefix.display().to_string().color(Color::Cyan);
+                    paths.iter().map(|path| {
+                        let stripped = path.strip_prefix(&common_prefix);
+                        if let Ok(stripped) = stripped {
+                            format!("{}{}{}", common_prefix_string, MAIN_SEPARATOR_STR, stripped.display())
+                        } else {
+                            path.display().to_string()
+                        }
+                    }).collect()
+                };
+
+                println!(
+                    "{}{}{}",
+                    if args.display_count {
+                        format!("{}{}", count, &args.separator)
+                    } else {
+                        String::new()
+                    },
+                    path_strings.join(&args.separator),
+                    &args.group_separator,
+                );
+            }
+
+            if errors.len() > 0 {
+                eprintln!("{}", format!("{} ERRORS {}", "=".repeat(30), "=".repeat(30)).color(Color::BrightRed));
+                for error in &errors {
+                    eprintln!("{}", error);
                 }
-            }).collect()
-        };
-
-        println!(
-            "{}{}{}",
-            if args.display_count {
-                format!("{}{}", count, &args.separator)
-            } else {
-                String::new()
-            },
-            path_strings.join(&args.separator),
-            &args.group_separator,
-        );
+                eprintln!("{}", String::from("Results may be invalid due to the above errors.").color(Color::BrightRed))
+            }
+
+            if args.action != Action::Report && args.dry_run {
+                for path in planned_duplicates(&reported, args.keep) {
+                    println!("{} {}", args.action, path.display());
+                }
+            }
+        }
+        Format::Json => {
+            let groups: Vec<DuplicateGroupJson> = reported
+                .iter()
+                .map(|paths| {
+                    let count = paths.len();
+                    let size = paths.first().and_then(|path| fs::metadata(path).ok()).map_or(0, |md| md.len());
+                    DuplicateGroupJson {
+                        count,
+                        reclaimable_bytes: size * (count - 1) as u64,
+                        common_prefix: get_common_prefix(paths).display().to_string(),
+                        paths: paths.iter().map(|path| path.display().to_string()).collect(),
+                    }
+                })
+                .collect();
+            let json_errors: Vec<PathIoErrorJson> = errors.iter().map(PathIoErrorJson::from).collect();
+
+            let mut output = serde_json::json!({ "groups": groups, "errors": json_errors });
+            if args.action != Action::Report && args.dry_run {
+                let planned: Vec<serde_json::Value> = planned_duplicates(&reported, args.keep)
+                    .into_iter()
+                    .map(|path| serde_json::json!({ "action": args.action.to_string(), "path": path.display().to_string() }))
+                    .collect();
+                output["planned_actions"] = serde_json::Value::Array(planned);
+            }
+            match serde_json::to_string(&output) {
+                Ok(text) => println!("{text}"),
+                Err(err) => eprintln!("{}", err.to_string().color(Color::BrightRed)),
+            }
+        }
+    }
+
+    if args.action != Action::Report && !args.dry_run {
+        let (_, action_errors) = apply_action(&reported, args.action, args.keep);
+        if action_errors.len() > 0 {
+            eprintln!("{}", format!("{} ACTION ERRORS {}", "=".repeat(30), "=".repeat(30)).color(Color::BrightRed));
+            for error in action_errors {
+                eprintln!("{}", error);
+            }
+        }
     }
 
-    if errors.len() > 0 {
-        eprintln!("{}", format!("{} ERRORS {}", "=".repeat(30), "=".repeat(30)).color(Color::BrightRed));
-        for error in errors {
-            eprintln!("{}", error);
+    if let (Some(cache_path), Some(mut hash_cache)) = (args.cache, hash_cache) {
+        if let Err(err) = hash_cache.save(&cache_path) {
+            eprintln!("{}", format!("{}: {}", cache_path.display(), err).color(Color::BrightRed));
         }
-        eprintln!("{}", String::from("Results may be invalid due to the above errors.").color(Color::BrightRed))
     }
 }
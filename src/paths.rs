@@ -5,8 +5,17 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use rayon::prelude::*;
+
+use crate::filter::TraversalFilter;
+
 /// Recursively finds all of the descendants of a file.
 ///
+/// Subdirectories are fanned out across the global rayon thread pool, which
+/// can be sized with `rayon::ThreadPoolBuilder::build_global`. Directories
+/// and files excluded by `filter` are skipped during the walk, so they are
+/// never read.
+///
 /// ## Example:
 /// ```no_run
 /// let paths = vec![
@@ -18,7 +27,7 @@ use std::{
 ///     Path::from("files/more_files/even_more_files/f.txt")
 /// ];
 ///
-/// let descendants = get_descendants(Path::from("files/more_files"));
+/// let descendants = get_descendants(Path::from("files/more_files"), None, &TraversalFilter::default());
 /// let expected = vec![
 ///     Path::from("files/more_files/c.txt"),
 ///     Path::from("files/more_files/d.txt"),
@@ -27,10 +36,10 @@ use std::{
 /// ];
 /// assert!(descendants == expected);
 ///
-/// let descendants = get_descendants(Path::from("files"));
+/// let descendants = get_descendants(Path::from("files"), None, &TraversalFilter::default());
 /// assert!(descendants == paths);
 /// ```
-pub fn get_descendants(base_path: &Path, max_depth: Option<usize>) -> Vec<PathBuf> {
+pub fn get_descendants(base_path: &Path, max_depth: Option<usize>, filter: &TraversalFilter) -> Vec<PathBuf> {
     if let Some(max_depth) = max_depth {
         if max_depth == 0 {
             return Vec::new();
@@ -38,17 +47,25 @@ pub fn get_descendants(base_path: &Path, max_depth: Option<usize>) -> Vec<PathBu
     }
 
     if base_path.is_dir() {
+        if filter.excludes_dir(base_path) {
+            return Vec::new();
+        }
+
         if let Ok(dir_iter) = fs::read_dir(base_path) {
-            dir_iter
-                .flatten()
-                .map(|dir_entry| get_descendants(&dir_entry.path(), max_depth.map(|d| d - 1)))
+            let entries: Vec<_> = dir_iter.flatten().collect();
+            entries
+                .par_iter()
+                .map(|dir_entry| get_descendants(&dir_entry.path(), max_depth.map(|d| d - 1), filter))
                 .flatten()
                 .collect()
         } else {
             Vec::new()
         }
     } else {
-        vec![PathBuf::from(base_path)]
+        match fs::metadata(base_path) {
+            Ok(metadata) if filter.allows_file(base_path, metadata.len()) => vec![PathBuf::from(base_path)],
+            _ => Vec::new(),
+        }
     }
 }
 